@@ -0,0 +1,529 @@
+// Real-time collaboration. An optional WebSocket session server assigns
+// each connecting client a replica id and relays every mutation as a typed
+// `Operation`; peers (and the host's own local edits) apply incoming ops to
+// the shared canvas with last-writer-wins per box/connection, keyed by a
+// monotonically increasing per-replica sequence number. On (re)connect the
+// client sends its last-seen sequence so the host can replay the missed
+// suffix, or fall back to a full snapshot if the client is too far behind.
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use futures_util::{SinkExt, StreamExt};
+use log::error;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::{Box as CanvasBox, CanvasState, Connection as CanvasConnection};
+
+const PEER_STATE_CHANGED_EVENT: &str = "peer-state-changed";
+/// How far behind a reconnecting client's last-seen sequence can be before
+/// the host gives up replaying and sends a full snapshot instead.
+const REPLAY_WINDOW: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Operation {
+    BoxUpserted { box_: CanvasBox, replica_id: u32, seq: u64 },
+    BoxDeleted { id: String, replica_id: u32, seq: u64 },
+    ConnectionUpserted { connection: CanvasConnection, replica_id: u32, seq: u64 },
+    ConnectionDeleted { from: String, to: String, replica_id: u32, seq: u64 },
+}
+
+impl Operation {
+    fn seq(&self) -> u64 {
+        match self {
+            Operation::BoxUpserted { seq, .. }
+            | Operation::BoxDeleted { seq, .. }
+            | Operation::ConnectionUpserted { seq, .. }
+            | Operation::ConnectionDeleted { seq, .. } => *seq,
+        }
+    }
+
+    fn replica_id(&self) -> u32 {
+        match self {
+            Operation::BoxUpserted { replica_id, .. }
+            | Operation::BoxDeleted { replica_id, .. }
+            | Operation::ConnectionUpserted { replica_id, .. }
+            | Operation::ConnectionDeleted { replica_id, .. } => *replica_id,
+        }
+    }
+
+    /// Conflict key: ops touching the same box/connection race each other;
+    /// ops on different keys never need to be compared.
+    fn conflict_key(&self) -> String {
+        match self {
+            Operation::BoxUpserted { box_, .. } => format!("box:{}", box_.id),
+            Operation::BoxDeleted { id, .. } => format!("box:{}", id),
+            Operation::ConnectionUpserted { connection, .. } => format!("conn:{}:{}", connection.from, connection.to),
+            Operation::ConnectionDeleted { from, to, .. } => format!("conn:{}:{}", from, to),
+        }
+    }
+
+    fn apply(&self, canvas: &mut CanvasState) {
+        match self {
+            Operation::BoxUpserted { box_, .. } => {
+                canvas.boxes.retain(|b| b.id != box_.id);
+                canvas.boxes.push(box_.clone());
+            }
+            Operation::BoxDeleted { id, .. } => {
+                canvas.boxes.retain(|b| &b.id != id);
+                canvas.connections.retain(|c| &c.from != id && &c.to != id);
+            }
+            Operation::ConnectionUpserted { connection, .. } => {
+                canvas.connections.retain(|c| !(c.from == connection.from && c.to == connection.to));
+                canvas.connections.push(connection.clone());
+            }
+            Operation::ConnectionDeleted { from, to, .. } => {
+                canvas.connections.retain(|c| !(&c.from == from && &c.to == to));
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum WireMessage {
+    /// `None` means a fresh join with no prior state at all (the op log
+    /// only covers mutations made *during* a session, never the canvas a
+    /// host started with) -- the host must always answer with a full
+    /// `Snapshot` rather than a suffix of the log.
+    Hello { last_seen_seq: Option<u64> },
+    Welcome { replica_id: u32 },
+    Ops(Vec<Operation>),
+    Snapshot(CanvasState),
+}
+
+/// Tracks, per conflict key, the (seq, replica_id) and value of the op last
+/// applied so a late-arriving op that loses the last-writer-wins comparison
+/// is dropped -- and, for a caller that applied its own op optimistically
+/// before finding out it lost, can be told what the winning value actually
+/// was so it can revert to it.
+#[derive(Default)]
+struct LwwTable(HashMap<String, (u64, u32, Operation)>);
+
+impl LwwTable {
+    /// Records `op` as the latest write for its conflict key if it's newer
+    /// than what's recorded. Returns `None` when `op` wins, or `Some` of the
+    /// op that actually won when `op` is stale.
+    fn accept(&mut self, op: &Operation) -> Option<Operation> {
+        let key = op.conflict_key();
+        let candidate = (op.seq(), op.replica_id());
+        if let Some((seq, replica_id, winner)) = self.0.get(&key) {
+            if candidate <= (*seq, *replica_id) {
+                return Some(winner.clone());
+            }
+        }
+        self.0.insert(key, (candidate.0, candidate.1, op.clone()));
+        None
+    }
+}
+
+struct HostState {
+    canvas: Arc<Mutex<CanvasState>>,
+    next_replica_id: AtomicU32,
+    log: Mutex<Vec<Operation>>,
+    lww: Mutex<LwwTable>,
+    peers: Mutex<HashMap<u32, mpsc::UnboundedSender<WireMessage>>>,
+}
+
+/// A live collaboration session: either hosting (accepting peers) or joined
+/// to someone else's host. Dropping it tears down the background task(s).
+pub enum CollabHandle {
+    Host { state: Arc<HostState>, next_seq: Arc<AtomicU64>, _shutdown: tokio::sync::oneshot::Sender<()> },
+    Client { replica_id: Arc<AtomicU32>, next_seq: Arc<AtomicU64>, outbound: mpsc::UnboundedSender<Operation>, _shutdown: tokio::sync::oneshot::Sender<()> },
+}
+
+impl CollabHandle {
+    pub fn replica_id(&self) -> u32 {
+        match self {
+            // The host is always replica 0; peers are assigned 1, 2, ...
+            CollabHandle::Host { .. } => 0,
+            CollabHandle::Client { replica_id, .. } => replica_id.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Allocates the next sequence number for an op originating locally.
+    pub fn next_seq(&self) -> u64 {
+        let counter = match self {
+            CollabHandle::Host { next_seq, .. } => next_seq,
+            CollabHandle::Client { next_seq, .. } => next_seq,
+        };
+        counter.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// Broadcasts a locally-originated op to every peer. The caller has
+    /// already applied `op` to the canvas directly (it's a normal command
+    /// handler mutation, collab-aware or not); on the host this runs it
+    /// through the same `LwwTable` gate as a peer's op (collab.rs's
+    /// `handle_peer` `Ops` branch), and if it turns out to be stale next to
+    /// a peer's edit, re-applies the actual winner to `state.canvas` so the
+    /// host doesn't diverge from what it just told peers NOT to accept.
+    pub fn broadcast_local(&self, op: Operation) {
+        match self {
+            CollabHandle::Host { state, .. } => {
+                match state.lww.lock().unwrap().accept(&op) {
+                    None => {
+                        push_to_log(&state.log, op.clone());
+                        let msg = WireMessage::Ops(vec![op]);
+                        for sender in state.peers.lock().unwrap().values() {
+                            let _ = sender.send(msg.clone());
+                        }
+                    }
+                    Some(winner) => {
+                        winner.apply(&mut state.canvas.lock().unwrap());
+                    }
+                }
+            }
+            CollabHandle::Client { outbound, .. } => {
+                let _ = outbound.send(op);
+            }
+        }
+    }
+}
+
+fn push_to_log(log: &Mutex<Vec<Operation>>, op: Operation) {
+    let mut log = log.lock().unwrap();
+    log.push(op);
+    if log.len() > REPLAY_WINDOW {
+        let overflow = log.len() - REPLAY_WINDOW;
+        log.drain(0..overflow);
+    }
+}
+
+async fn handle_peer(stream: TcpStream, host: Arc<HostState>, replica_id: u32, app_handle: AppHandle) {
+    let ws_stream = match tokio_tungstenite::accept_async(stream).await {
+        Ok(ws) => ws,
+        Err(e) => {
+            error!("collab: failed to accept peer connection: {e}");
+            return;
+        }
+    };
+    let (mut write, mut read) = ws_stream.split();
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<WireMessage>();
+    let _ = tx.send(WireMessage::Welcome { replica_id });
+    host.peers.lock().unwrap().insert(replica_id, tx);
+
+    let mut write_task = tokio::spawn(async move {
+        while let Some(msg) = rx.recv().await {
+            let text = serde_json::to_string(&msg).unwrap();
+            if write.send(Message::Text(text)).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    while let Some(Ok(msg)) = read.next().await {
+        let Message::Text(text) = msg else { continue };
+        match serde_json::from_str::<WireMessage>(&text) {
+            Ok(WireMessage::Hello { last_seen_seq }) => {
+                let replay = match last_seen_seq {
+                    None => None,
+                    Some(last_seen_seq) => {
+                        let log = host.log.lock().unwrap();
+                        let earliest = log.first().map(|op| op.seq());
+                        match earliest {
+                            Some(earliest) if last_seen_seq + 1 >= earliest => {
+                                Some(log.iter().filter(|op| op.seq() > last_seen_seq).cloned().collect::<Vec<_>>())
+                            }
+                            _ => None,
+                        }
+                    }
+                };
+                if let Some(peer) = host.peers.lock().unwrap().get(&replica_id) {
+                    match replay {
+                        Some(ops) => {
+                            let _ = peer.send(WireMessage::Ops(ops));
+                        }
+                        None => {
+                            let snapshot = host.canvas.lock().unwrap().clone();
+                            let _ = peer.send(WireMessage::Snapshot(snapshot));
+                        }
+                    }
+                }
+            }
+            Ok(WireMessage::Ops(ops)) => {
+                let mut accepted = Vec::new();
+                {
+                    let mut canvas = host.canvas.lock().unwrap();
+                    let mut lww = host.lww.lock().unwrap();
+                    for op in ops {
+                        if lww.accept(&op).is_none() {
+                            op.apply(&mut canvas);
+                            accepted.push(op);
+                        }
+                    }
+                }
+                if !accepted.is_empty() {
+                    for op in &accepted {
+                        push_to_log(&host.log, op.clone());
+                    }
+                    let peers = host.peers.lock().unwrap();
+                    for (&peer_id, sender) in peers.iter() {
+                        if peer_id != replica_id {
+                            let _ = sender.send(WireMessage::Ops(accepted.clone()));
+                        }
+                    }
+                    drop(peers);
+                    let _ = app_handle.emit(PEER_STATE_CHANGED_EVENT, ());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    host.peers.lock().unwrap().remove(&replica_id);
+    write_task.abort();
+}
+
+/// Starts listening on `port`, accepting peer connections and wiring each
+/// into the shared canvas via `handle_peer`.
+pub fn host(app_handle: AppHandle, canvas: Arc<Mutex<CanvasState>>, port: u16) -> Result<CollabHandle, String> {
+    let host_state = Arc::new(HostState {
+        canvas,
+        next_replica_id: AtomicU32::new(1),
+        log: Mutex::new(Vec::new()),
+        lww: Mutex::new(LwwTable::default()),
+        peers: Mutex::new(HashMap::new()),
+    });
+
+    let (shutdown_tx, mut shutdown_rx) = tokio::sync::oneshot::channel();
+    let accept_state = host_state.clone();
+    let accept_app_handle = app_handle.clone();
+    std::thread::spawn(move || {
+        let runtime = match tokio::runtime::Runtime::new() {
+            Ok(rt) => rt,
+            Err(e) => {
+                error!("collab: failed to start runtime: {e}");
+                return;
+            }
+        };
+        runtime.block_on(async move {
+            let listener = match TcpListener::bind(("0.0.0.0", port)).await {
+                Ok(l) => l,
+                Err(e) => {
+                    error!("collab: failed to bind port {port}: {e}");
+                    return;
+                }
+            };
+            loop {
+                tokio::select! {
+                    _ = &mut shutdown_rx => break,
+                    accepted = listener.accept() => {
+                        let Ok((stream, _addr)) = accepted else { continue };
+                        let replica_id = accept_state.next_replica_id.fetch_add(1, Ordering::SeqCst);
+                        let peer_state = accept_state.clone();
+                        let peer_app_handle = accept_app_handle.clone();
+                        tokio::spawn(handle_peer(stream, peer_state, replica_id, peer_app_handle));
+                    }
+                }
+            }
+        });
+    });
+
+    Ok(CollabHandle::Host {
+        state: host_state,
+        next_seq: Arc::new(AtomicU64::new(0)),
+        _shutdown: shutdown_tx,
+    })
+}
+
+/// Connects to a host at `url`, replays/snapshots to catch up, and applies
+/// incoming ops to `canvas` as they arrive.
+pub fn join(app_handle: AppHandle, canvas: Arc<Mutex<CanvasState>>, url: String, last_seen_seq: Option<u64>) -> Result<CollabHandle, String> {
+    let (shutdown_tx, mut shutdown_rx) = tokio::sync::oneshot::channel();
+    let (outbound_tx, mut outbound_rx) = mpsc::unbounded_channel::<Operation>();
+    let replica_id = Arc::new(AtomicU32::new(0));
+    let replica_id_for_task = replica_id.clone();
+
+    std::thread::spawn(move || {
+        let runtime = match tokio::runtime::Runtime::new() {
+            Ok(rt) => rt,
+            Err(e) => {
+                error!("collab: failed to start runtime: {e}");
+                return;
+            }
+        };
+        runtime.block_on(async move {
+            let (ws_stream, _) = match tokio_tungstenite::connect_async(&url).await {
+                Ok(pair) => pair,
+                Err(e) => {
+                    error!("collab: failed to connect to {url}: {e}");
+                    return;
+                }
+            };
+            let (mut write, mut read) = ws_stream.split();
+            let hello = serde_json::to_string(&WireMessage::Hello { last_seen_seq }).unwrap();
+            if write.send(Message::Text(hello)).await.is_err() {
+                return;
+            }
+
+            // Mirrors the host's `LwwTable` gate (collab.rs's `handle_peer`
+            // `Ops` branch) so an out-of-order op delivery can't apply a
+            // stale write on top of one this replica already has.
+            let mut lww = LwwTable::default();
+
+            loop {
+                tokio::select! {
+                    _ = &mut shutdown_rx => break,
+                    outgoing = outbound_rx.recv() => {
+                        let Some(op) = outgoing else { break };
+                        let msg = serde_json::to_string(&WireMessage::Ops(vec![op])).unwrap();
+                        if write.send(Message::Text(msg)).await.is_err() {
+                            break;
+                        }
+                    }
+                    incoming = read.next() => {
+                        let Some(Ok(Message::Text(text))) = incoming else { break };
+                        match serde_json::from_str::<WireMessage>(&text) {
+                            Ok(WireMessage::Welcome { replica_id: assigned }) => {
+                                replica_id_for_task.store(assigned, Ordering::SeqCst);
+                            }
+                            Ok(WireMessage::Snapshot(state)) => {
+                                *canvas.lock().unwrap() = state;
+                                lww = LwwTable::default();
+                                let _ = app_handle.emit(PEER_STATE_CHANGED_EVENT, ());
+                            }
+                            Ok(WireMessage::Ops(ops)) => {
+                                let mut guard = canvas.lock().unwrap();
+                                for op in &ops {
+                                    if lww.accept(op).is_none() {
+                                        op.apply(&mut guard);
+                                    }
+                                }
+                                drop(guard);
+                                let _ = app_handle.emit(PEER_STATE_CHANGED_EVENT, ());
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+        });
+    });
+
+    Ok(CollabHandle::Client {
+        replica_id,
+        next_seq: Arc::new(AtomicU64::new(last_seen_seq.unwrap_or(0))),
+        outbound: outbound_tx,
+        _shutdown: shutdown_tx,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_box(id: &str) -> CanvasBox {
+        CanvasBox {
+            id: id.to_string(),
+            x: 0,
+            y: 0,
+            width: 10,
+            height: 10,
+            text: String::new(),
+            selected: false,
+            color: None,
+        }
+    }
+
+    #[test]
+    fn box_upserted_replaces_existing_box_by_id() {
+        let mut canvas = CanvasState::default();
+        canvas.boxes.push(sample_box("a"));
+
+        let mut updated = sample_box("a");
+        updated.x = 42;
+        Operation::BoxUpserted { box_: updated, replica_id: 1, seq: 1 }.apply(&mut canvas);
+
+        assert_eq!(canvas.boxes.len(), 1);
+        assert_eq!(canvas.boxes[0].x, 42);
+    }
+
+    #[test]
+    fn box_deleted_also_drops_its_connections() {
+        let mut canvas = CanvasState::default();
+        canvas.boxes.push(sample_box("a"));
+        canvas.boxes.push(sample_box("b"));
+        canvas.connections.push(CanvasConnection {
+            from: "a".to_string(),
+            to: "b".to_string(),
+            r#type: crate::ConnectionType::Forward,
+        });
+
+        Operation::BoxDeleted { id: "a".to_string(), replica_id: 1, seq: 2 }.apply(&mut canvas);
+
+        assert_eq!(canvas.boxes.len(), 1);
+        assert!(canvas.connections.is_empty());
+    }
+
+    #[test]
+    fn lww_table_accepts_higher_seq_and_rejects_stale_one() {
+        let mut lww = LwwTable::default();
+        let first = Operation::BoxUpserted { box_: sample_box("a"), replica_id: 1, seq: 5 };
+        let stale = Operation::BoxUpserted { box_: sample_box("a"), replica_id: 1, seq: 3 };
+        let newer = Operation::BoxUpserted { box_: sample_box("a"), replica_id: 2, seq: 6 };
+
+        assert!(lww.accept(&first).is_none());
+        assert_eq!(lww.accept(&stale), Some(first));
+        assert!(lww.accept(&newer).is_none());
+    }
+
+    #[test]
+    fn lww_table_breaks_seq_ties_by_replica_id() {
+        let mut lww = LwwTable::default();
+        let low_replica = Operation::BoxUpserted { box_: sample_box("a"), replica_id: 1, seq: 5 };
+        let high_replica = Operation::BoxUpserted { box_: sample_box("a"), replica_id: 2, seq: 5 };
+
+        assert!(lww.accept(&low_replica).is_none());
+        assert!(lww.accept(&high_replica).is_none());
+    }
+
+    #[test]
+    fn lww_table_tracks_keys_independently() {
+        let mut lww = LwwTable::default();
+        let box_op = Operation::BoxUpserted { box_: sample_box("a"), replica_id: 1, seq: 10 };
+        let conn_op = Operation::ConnectionDeleted { from: "a".to_string(), to: "b".to_string(), replica_id: 1, seq: 1 };
+
+        assert!(lww.accept(&box_op).is_none());
+        assert!(lww.accept(&conn_op).is_none());
+    }
+
+    #[test]
+    fn broadcast_local_reverts_host_canvas_when_local_edit_loses_lww() {
+        let canvas = Arc::new(Mutex::new(CanvasState::default()));
+        let winning_box = {
+            let mut b = sample_box("a");
+            b.x = 100;
+            b
+        };
+        canvas.lock().unwrap().boxes.push(winning_box.clone());
+
+        let state = Arc::new(HostState {
+            canvas: canvas.clone(),
+            next_replica_id: AtomicU32::new(1),
+            log: Mutex::new(Vec::new()),
+            lww: Mutex::new(LwwTable::default()),
+            peers: Mutex::new(HashMap::new()),
+        });
+        // A peer's edit has already been recorded as the winner for "a" at
+        // seq 10. The host then "applies" a stale local edit (seq 5) to its
+        // own canvas before broadcasting, simulating a normal command
+        // handler that mutates first and broadcasts after.
+        state.lww.lock().unwrap().accept(&Operation::BoxUpserted {
+            box_: winning_box.clone(),
+            replica_id: 2,
+            seq: 10,
+        });
+        let mut stale_box = sample_box("a");
+        stale_box.x = 1;
+        canvas.lock().unwrap().boxes[0].x = 1;
+
+        let handle = CollabHandle::Host { state, next_seq: Arc::new(AtomicU64::new(0)), _shutdown: tokio::sync::oneshot::channel().0 };
+        handle.broadcast_local(Operation::BoxUpserted { box_: stale_box, replica_id: 1, seq: 5 });
+
+        assert_eq!(canvas.lock().unwrap().boxes[0].x, 100);
+    }
+}
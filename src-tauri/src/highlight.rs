@@ -0,0 +1,128 @@
+// Syntax highlighting for box text, following the same `syntect`-based
+// approach yazi uses for file previews: load the bundled syntax set and a
+// theme once at startup, then highlight on demand. When no language hint is
+// given, the syntax is inferred from a fenced-code marker or the first line
+// of the text; anything that still doesn't match a known syntax renders as
+// a single unstyled span instead of guessing.
+use serde::{Deserialize, Serialize};
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{FontStyle, Theme, ThemeSet};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+
+const DEFAULT_THEME: &str = "base16-ocean.dark";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HighlightSpan {
+    pub text: String,
+    pub color: String,
+    pub bold: bool,
+    pub italic: bool,
+}
+
+fn plain_span(text: &str) -> Vec<HighlightSpan> {
+    vec![HighlightSpan {
+        text: text.to_string(),
+        color: "#ffffff".to_string(),
+        bold: false,
+        italic: false,
+    }]
+}
+
+/// Holds the syntax definitions and theme loaded once at startup; both are
+/// read-only after construction so no lock is needed around them.
+pub struct HighlightService {
+    syntax_set: SyntaxSet,
+    theme: Theme,
+}
+
+impl Default for HighlightService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HighlightService {
+    pub fn new() -> Self {
+        let syntax_set = SyntaxSet::load_defaults_newlines();
+        let theme = ThemeSet::load_defaults().themes[DEFAULT_THEME].clone();
+        Self { syntax_set, theme }
+    }
+
+    /// Picks a syntax for `text`, preferring an explicit `hint` (a language
+    /// name or file extension), then the literal first line, and finally
+    /// falling back to plain text.
+    fn resolve_syntax(&self, text: &str, hint: Option<&str>) -> &SyntaxReference {
+        if let Some(hint) = hint {
+            if let Some(syntax) = self.syntax_set.find_syntax_by_token(hint) {
+                return syntax;
+            }
+        }
+
+        if let Some(first_line) = text.lines().next() {
+            if let Some(syntax) = self.syntax_set.find_syntax_by_first_line(first_line) {
+                return syntax;
+            }
+        }
+
+        self.syntax_set.find_syntax_plain_text()
+    }
+
+    /// Highlights `text`, returning one span per styled run across all
+    /// lines. Plain prose (or anything syntect can't parse) comes back as a
+    /// single unstyled span. A leading ```` ```lang ```` fence (and its
+    /// matching closing fence) is used only to resolve the syntax and
+    /// stripped out of the highlighted body, so it never renders as a
+    /// literal line of code.
+    pub fn highlight(&self, text: &str, hint: Option<&str>) -> Vec<HighlightSpan> {
+        let (body, fence_lang) = strip_fence(text);
+        let syntax = self.resolve_syntax(body, hint.or(fence_lang));
+        if syntax.name == "Plain Text" {
+            return plain_span(body);
+        }
+
+        let mut highlighter = HighlightLines::new(syntax, &self.theme);
+        let mut spans = Vec::new();
+        for line in LinesWithEndings::from(body) {
+            let ranges = match highlighter.highlight_line(line, &self.syntax_set) {
+                Ok(ranges) => ranges,
+                Err(_) => return plain_span(body),
+            };
+            for (style, piece) in ranges {
+                spans.push(HighlightSpan {
+                    text: piece.to_string(),
+                    color: format!(
+                        "#{:02x}{:02x}{:02x}",
+                        style.foreground.r, style.foreground.g, style.foreground.b
+                    ),
+                    bold: style.font_style.contains(FontStyle::BOLD),
+                    italic: style.font_style.contains(FontStyle::ITALIC),
+                });
+            }
+        }
+        spans
+    }
+}
+
+/// If `text` opens with a fenced-code marker (` ```lang `), returns the body
+/// with that line -- and a matching trailing ` ``` ` line, if present --
+/// removed, along with the language token from the fence. Otherwise returns
+/// `text` unchanged.
+fn strip_fence(text: &str) -> (&str, Option<&str>) {
+    let first_line_end = text.find('\n').map(|i| i + 1).unwrap_or(text.len());
+    let first_line = text[..first_line_end].trim();
+    let Some(lang) = first_line.strip_prefix("```") else {
+        return (text, None);
+    };
+    let lang = lang.trim();
+    let lang = if lang.is_empty() { None } else { Some(lang) };
+
+    let mut body = &text[first_line_end..];
+    let trimmed_end = body.trim_end_matches(['\n', '\r']);
+    let last_line_start = trimmed_end.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    if trimmed_end[last_line_start..].trim() == "```" {
+        body = &body[..last_line_start];
+    }
+
+    (body, lang)
+}
@@ -0,0 +1,248 @@
+// Event-sourced undo/redo. Every box and connection edit command appends a
+// reversible `Op` to an append-only log instead of just overwriting
+// `CanvasState` (selection changes are not recorded), so
+// `undo`/`redo` can pop an entry, apply its inverse (or, for redo, reapply
+// it), and move it to the opposite stack. A fresh mutation always clears the
+// redo stack.
+use std::collections::VecDeque;
+
+use crate::{Box as CanvasBox, CanvasState, Connection as CanvasConnection};
+
+const DEFAULT_CAP: usize = 200;
+
+/// A single box's position before and after a move, used as one entry of a
+/// compound `BoxMoved` cascade so one undo reverses every displaced box at
+/// once.
+#[derive(Debug, Clone)]
+pub struct BoxDelta {
+    pub id: String,
+    pub old_x: i32,
+    pub old_y: i32,
+    pub new_x: i32,
+    pub new_y: i32,
+}
+
+#[derive(Debug, Clone)]
+pub enum Op {
+    BoxAdded { box_: CanvasBox },
+    BoxDeleted { box_: CanvasBox, connections: Vec<CanvasConnection> },
+    BoxMoved { moves: Vec<BoxDelta> },
+    TextChanged { id: String, old_text: String, old_width: i32, old_height: i32, new_text: String, new_width: i32, new_height: i32 },
+    ConnectionsChanged { before: Vec<CanvasConnection>, after: Vec<CanvasConnection> },
+}
+
+impl Op {
+    fn apply_inverse(&self, canvas: &mut CanvasState) {
+        match self {
+            Op::BoxAdded { box_ } => {
+                canvas.boxes.retain(|b| b.id != box_.id);
+            }
+            Op::BoxDeleted { box_, connections } => {
+                canvas.boxes.push(box_.clone());
+                canvas.connections.extend(connections.iter().cloned());
+            }
+            Op::BoxMoved { moves } => {
+                for delta in moves {
+                    if let Some(b) = canvas.boxes.iter_mut().find(|b| b.id == delta.id) {
+                        b.x = delta.old_x;
+                        b.y = delta.old_y;
+                    }
+                }
+            }
+            Op::TextChanged { id, old_text, old_width, old_height, .. } => {
+                if let Some(b) = canvas.boxes.iter_mut().find(|b| &b.id == id) {
+                    b.text = old_text.clone();
+                    b.width = *old_width;
+                    b.height = *old_height;
+                }
+            }
+            Op::ConnectionsChanged { before, .. } => {
+                canvas.connections = before.clone();
+            }
+        }
+    }
+
+    fn apply_forward(&self, canvas: &mut CanvasState) {
+        match self {
+            Op::BoxAdded { box_ } => {
+                canvas.boxes.push(box_.clone());
+            }
+            Op::BoxDeleted { box_, .. } => {
+                let id = box_.id.clone();
+                canvas.boxes.retain(|b| b.id != id);
+                canvas.connections.retain(|c| c.from != id && c.to != id);
+            }
+            Op::BoxMoved { moves } => {
+                for delta in moves {
+                    if let Some(b) = canvas.boxes.iter_mut().find(|b| b.id == delta.id) {
+                        b.x = delta.new_x;
+                        b.y = delta.new_y;
+                    }
+                }
+            }
+            Op::TextChanged { id, new_text, new_width, new_height, .. } => {
+                if let Some(b) = canvas.boxes.iter_mut().find(|b| &b.id == id) {
+                    b.text = new_text.clone();
+                    b.width = *new_width;
+                    b.height = *new_height;
+                }
+            }
+            Op::ConnectionsChanged { after, .. } => {
+                canvas.connections = after.clone();
+            }
+        }
+    }
+}
+
+/// The undo log plus redo stack, with a configurable cap on retained
+/// entries.
+pub struct History {
+    undo_stack: VecDeque<Op>,
+    redo_stack: Vec<Op>,
+    cap: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAP)
+    }
+}
+
+impl History {
+    pub fn new(cap: usize) -> Self {
+        Self {
+            undo_stack: VecDeque::new(),
+            redo_stack: Vec::new(),
+            cap,
+        }
+    }
+
+    /// Records a fresh mutation, discarding the redo stack.
+    pub fn push(&mut self, op: Op) {
+        self.undo_stack.push_back(op);
+        while self.undo_stack.len() > self.cap {
+            self.undo_stack.pop_front();
+        }
+        self.redo_stack.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.undo_stack.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.undo_stack.is_empty()
+    }
+
+    /// Drops all history, e.g. when a different document is loaded wholesale.
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+    }
+
+    pub fn undo(&mut self, canvas: &mut CanvasState) -> bool {
+        match self.undo_stack.pop_back() {
+            Some(op) => {
+                op.apply_inverse(canvas);
+                self.redo_stack.push(op);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn redo(&mut self, canvas: &mut CanvasState) -> bool {
+        match self.redo_stack.pop() {
+            Some(op) => {
+                op.apply_forward(canvas);
+                self.undo_stack.push_back(op);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_box(id: &str) -> CanvasBox {
+        CanvasBox {
+            id: id.to_string(),
+            x: 0,
+            y: 0,
+            width: 10,
+            height: 10,
+            text: String::new(),
+            selected: false,
+            color: None,
+        }
+    }
+
+    #[test]
+    fn undo_box_added_removes_it_and_redo_restores_it() {
+        let mut history = History::new(10);
+        let mut canvas = CanvasState::default();
+        let b = sample_box("a");
+        canvas.boxes.push(b.clone());
+        history.push(Op::BoxAdded { box_: b });
+
+        assert!(history.undo(&mut canvas));
+        assert!(canvas.boxes.is_empty());
+
+        assert!(history.redo(&mut canvas));
+        assert_eq!(canvas.boxes.len(), 1);
+    }
+
+    #[test]
+    fn box_moved_cascade_reverses_every_delta_together() {
+        let mut history = History::new(10);
+        let mut canvas = CanvasState::default();
+        canvas.boxes.push(sample_box("a"));
+        canvas.boxes.push(sample_box("b"));
+        canvas.boxes[0].x = 50;
+        canvas.boxes[1].x = 60;
+        history.push(Op::BoxMoved {
+            moves: vec![
+                BoxDelta { id: "a".to_string(), old_x: 0, old_y: 0, new_x: 50, new_y: 0 },
+                BoxDelta { id: "b".to_string(), old_x: 10, old_y: 0, new_x: 60, new_y: 0 },
+            ],
+        });
+
+        history.undo(&mut canvas);
+        assert_eq!(canvas.boxes[0].x, 0);
+        assert_eq!(canvas.boxes[1].x, 10);
+    }
+
+    #[test]
+    fn pushing_a_new_op_clears_the_redo_stack() {
+        let mut history = History::new(10);
+        let mut canvas = CanvasState::default();
+        canvas.boxes.push(sample_box("a"));
+        history.push(Op::BoxAdded { box_: sample_box("a") });
+        history.undo(&mut canvas);
+
+        history.push(Op::BoxAdded { box_: sample_box("b") });
+
+        assert!(!history.redo(&mut canvas));
+    }
+
+    #[test]
+    fn cap_evicts_oldest_entries() {
+        let mut history = History::new(2);
+        for i in 0..5 {
+            history.push(Op::BoxAdded { box_: sample_box(&i.to_string()) });
+        }
+        assert_eq!(history.len(), 2);
+    }
+
+    #[test]
+    fn undo_and_redo_on_empty_history_are_no_ops() {
+        let mut history = History::new(10);
+        let mut canvas = CanvasState::default();
+        assert!(!history.undo(&mut canvas));
+        assert!(!history.redo(&mut canvas));
+        assert!(history.is_empty());
+    }
+}
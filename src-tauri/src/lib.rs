@@ -2,11 +2,25 @@
 use log::{info, error, debug};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet, VecDeque};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use tauri::{Emitter, State, Wry, Builder, Manager};
 use std::fs;
 use std::path::PathBuf;
 
+mod collab;
+mod highlight;
+mod history;
+mod persistence;
+mod search;
+mod walker;
+mod watcher;
+use collab::{CollabHandle, Operation};
+use highlight::{HighlightService, HighlightSpan};
+use history::{BoxDelta, History, Op};
+use persistence::CanvasStore;
+use search::SearchIndex;
+use watcher::WorkspaceWatcher;
+
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 pub enum ConnectionType {
     None,
@@ -22,7 +36,7 @@ struct BoundingBox {
     height: i32,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Box {
     pub id: String,
     pub x: i32,
@@ -34,7 +48,7 @@ pub struct Box {
     pub color: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Connection {
     pub from: String,
     pub to: String,
@@ -48,7 +62,129 @@ pub struct CanvasState {
 }
 
 pub struct AppState {
-    canvas_state: Mutex<CanvasState>,
+    canvas_state: Arc<Mutex<CanvasState>>,
+    workspace_watcher: Mutex<Option<WorkspaceWatcher>>,
+    canvas_store: Mutex<Option<CanvasStore>>,
+    search_index: Mutex<SearchIndex>,
+    history: Mutex<History>,
+    collab: Mutex<Option<CollabHandle>>,
+    highlighter: HighlightService,
+}
+
+/// Broadcasts a box upsert to any connected collaboration peers.
+fn broadcast_box(state: &State<AppState>, b: &Box) {
+    if let Some(collab) = state.collab.lock().unwrap().as_ref() {
+        let seq = collab.next_seq();
+        collab.broadcast_local(Operation::BoxUpserted { box_: b.clone(), replica_id: collab.replica_id(), seq });
+    }
+}
+
+fn broadcast_box_deletion(state: &State<AppState>, id: &str) {
+    if let Some(collab) = state.collab.lock().unwrap().as_ref() {
+        let seq = collab.next_seq();
+        collab.broadcast_local(Operation::BoxDeleted { id: id.to_string(), replica_id: collab.replica_id(), seq });
+    }
+}
+
+fn broadcast_connection(state: &State<AppState>, c: &Connection) {
+    if let Some(collab) = state.collab.lock().unwrap().as_ref() {
+        let seq = collab.next_seq();
+        collab.broadcast_local(Operation::ConnectionUpserted { connection: c.clone(), replica_id: collab.replica_id(), seq });
+    }
+}
+
+fn broadcast_connection_deletion(state: &State<AppState>, from: &str, to: &str) {
+    if let Some(collab) = state.collab.lock().unwrap().as_ref() {
+        let seq = collab.next_seq();
+        collab.broadcast_local(Operation::ConnectionDeleted { from: from.to_string(), to: to.to_string(), replica_id: collab.replica_id(), seq });
+    }
+}
+
+/// `undo`/`redo` replace the whole canvas in one shot rather than routing
+/// through one of the targeted commands above, so there's no single op to
+/// broadcast. Diff `before` against `after` and broadcast each changed
+/// box/connection individually, so peers converge on the same undo/redo
+/// result instead of silently diverging from the host.
+fn broadcast_canvas_diff(state: &State<AppState>, before: &CanvasState, after: &CanvasState) {
+    if state.collab.lock().unwrap().is_none() {
+        return;
+    }
+
+    let before_boxes: HashMap<&str, &Box> = before.boxes.iter().map(|b| (b.id.as_str(), b)).collect();
+    for b in &after.boxes {
+        if before_boxes.get(b.id.as_str()) != Some(&b) {
+            broadcast_box(state, b);
+        }
+    }
+    let after_box_ids: HashSet<&str> = after.boxes.iter().map(|b| b.id.as_str()).collect();
+    for b in &before.boxes {
+        if !after_box_ids.contains(b.id.as_str()) {
+            broadcast_box_deletion(state, &b.id);
+        }
+    }
+
+    let before_connections: HashMap<(&str, &str), &Connection> = before
+        .connections
+        .iter()
+        .map(|c| ((c.from.as_str(), c.to.as_str()), c))
+        .collect();
+    for c in &after.connections {
+        if before_connections.get(&(c.from.as_str(), c.to.as_str())) != Some(&c) {
+            broadcast_connection(state, c);
+        }
+    }
+    let after_connection_keys: HashSet<(&str, &str)> = after
+        .connections
+        .iter()
+        .map(|c| (c.from.as_str(), c.to.as_str()))
+        .collect();
+    for c in &before.connections {
+        if !after_connection_keys.contains(&(c.from.as_str(), c.to.as_str())) {
+            broadcast_connection_deletion(state, &c.from, &c.to);
+        }
+    }
+}
+
+/// Rebuilds the TF-IDF search index from the current boxes. Called after
+/// any command that adds, removes, or edits box text.
+fn reindex(state: &State<AppState>) {
+    let boxes = state.canvas_state.lock().unwrap().boxes.clone();
+    state.search_index.lock().unwrap().rebuild(&boxes);
+}
+
+/// Writes a box upsert through to the open canvas store, if any. Box-editing
+/// commands call this after updating the in-memory cache; the selection
+/// commands do not, so the stored `selected` flag can lag behind the cache.
+fn persist_box(state: &State<AppState>, b: &Box) {
+    if let Some(store) = state.canvas_store.lock().unwrap().as_ref() {
+        if let Err(e) = store.upsert_box(b) {
+            error!("failed to persist box {}: {}", b.id, e);
+        }
+    }
+}
+
+fn persist_box_deletion(state: &State<AppState>, id: &str) {
+    if let Some(store) = state.canvas_store.lock().unwrap().as_ref() {
+        if let Err(e) = store.delete_box(id) {
+            error!("failed to persist deletion of box {}: {}", id, e);
+        }
+    }
+}
+
+fn persist_connection(state: &State<AppState>, c: &Connection) {
+    if let Some(store) = state.canvas_store.lock().unwrap().as_ref() {
+        if let Err(e) = store.upsert_connection(c) {
+            error!("failed to persist connection {}->{}: {}", c.from, c.to, e);
+        }
+    }
+}
+
+fn persist_connection_deletion(state: &State<AppState>, from: &str, to: &str) {
+    if let Some(store) = state.canvas_store.lock().unwrap().as_ref() {
+        if let Err(e) = store.delete_connection(from, to) {
+            error!("failed to persist removal of connection {}->{}: {}", from, to, e);
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -58,10 +194,10 @@ struct ConfigFile {
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FileNode {
-    name: String,
-    path: String,
-    is_directory: bool,
-    children: Option<Vec<FileNode>>,
+    pub(crate) name: String,
+    pub(crate) path: String,
+    pub(crate) is_directory: bool,
+    pub(crate) children: Option<Vec<FileNode>>,
 }
 
 fn get_config_path(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
@@ -95,58 +231,37 @@ fn get_workspace_path(app_handle: tauri::AppHandle) -> Result<Option<String>, St
 }
 
 #[tauri::command]
-fn set_workspace_path(app_handle: tauri::AppHandle, path: String) -> Result<(), String> {
+fn set_workspace_path(app_handle: tauri::AppHandle, state: State<AppState>, path: String) -> Result<(), String> {
     let mut config = read_config(&app_handle).unwrap_or(ConfigFile { workspace_path: None });
     config.workspace_path = Some(path);
-    write_config(&app_handle, &config)
+    write_config(&app_handle, &config)?;
+
+    // The old watch, if any, now points at a stale workspace; drop it so a
+    // fresh one has to be started explicitly via `start_watching_workspace`.
+    *state.workspace_watcher.lock().unwrap() = None;
+    Ok(())
 }
 
 #[tauri::command]
-fn list_directory_contents(path: String) -> Result<Vec<FileNode>, String> {
-    info!("Reading directory contents for: {}", path);
-    let mut entries = Vec::new();
-
-    if !std::path::Path::new(&path).exists() {
-        return Ok(entries);
-    }
-
-    for entry in fs::read_dir(&path).map_err(|e| format!("Failed to read directory {}: {}", path, e))? {
-        let entry = entry.map_err(|e| e.to_string())?;
-        let path = entry.path();
-        let name = entry.file_name().to_string_lossy().to_string();
-        
-        if path.is_dir() {
-            // Recursively call for subdirectories
-            let children = list_directory_contents(path.to_string_lossy().to_string())?;
-            entries.push(FileNode {
-                name,
-                path: path.to_string_lossy().to_string(),
-                is_directory: true,
-                children: Some(children),
-            });
-        } else if let Some(extension) = path.extension() {
-            if extension == "kairo" {
-                entries.push(FileNode {
-                    name,
-                    path: path.to_string_lossy().to_string(),
-                    is_directory: false,
-                    children: None,
-                });
-            }
-        }
-    }
+fn start_watching_workspace(app_handle: tauri::AppHandle, state: State<AppState>) -> Result<(), String> {
+    let workspace_path = read_config(&app_handle)?
+        .workspace_path
+        .ok_or_else(|| "no workspace path is set".to_string())?;
 
-    entries.sort_by(|a, b| {
-        if a.is_directory == b.is_directory {
-            a.name.cmp(&b.name)
-        } else if a.is_directory {
-            std::cmp::Ordering::Less
-        } else {
-            std::cmp::Ordering::Greater
-        }
-    });
+    let new_watcher = watcher::start(app_handle, workspace_path)?;
+    *state.workspace_watcher.lock().unwrap() = Some(new_watcher);
+    Ok(())
+}
 
-    Ok(entries)
+#[tauri::command]
+fn stop_watching_workspace(state: State<AppState>) {
+    *state.workspace_watcher.lock().unwrap() = None;
+}
+
+#[tauri::command]
+pub(crate) fn list_directory_contents(path: String, max_depth: Option<usize>) -> Result<Vec<FileNode>, String> {
+    info!("Reading directory contents for: {}", path);
+    walker::walk(&path, max_depth)
 }
 
 fn do_boxes_intersect(a: &Box, b: &Box) -> bool {
@@ -162,24 +277,60 @@ fn get_full_state(state: State<AppState>) -> CanvasState {
 fn add_box(state: State<AppState>, id: String, x: i32, y: i32, width: i32, height: i32, text: String, selected: bool, color: Option<String>) {
     let mut canvas_state = state.canvas_state.lock().unwrap();
     let new_box = Box { id, x, y, width, height, text, selected, color };
-    canvas_state.boxes.push(new_box);
+    canvas_state.boxes.push(new_box.clone());
+    drop(canvas_state);
+    state.history.lock().unwrap().push(Op::BoxAdded { box_: new_box.clone() });
+    persist_box(&state, &new_box);
+    broadcast_box(&state, &new_box);
+    reindex(&state);
 }
 
 #[tauri::command]
 fn update_box_text(state: State<AppState>, id: String, text: String, width: i32, height: i32) {
     let mut canvas_state = state.canvas_state.lock().unwrap();
-    if let Some(box_to_update) = canvas_state.boxes.iter_mut().find(|b| b.id == id) {
+    let change = canvas_state.boxes.iter_mut().find(|b| b.id == id).map(|box_to_update| {
+        let old_text = box_to_update.text.clone();
+        let old_width = box_to_update.width;
+        let old_height = box_to_update.height;
         box_to_update.text = text;
         box_to_update.width = width;
         box_to_update.height = height;
+        (box_to_update.clone(), old_text, old_width, old_height)
+    });
+    drop(canvas_state);
+    if let Some((updated, old_text, old_width, old_height)) = change {
+        state.history.lock().unwrap().push(Op::TextChanged {
+            id: updated.id.clone(),
+            old_text,
+            old_width,
+            old_height,
+            new_text: updated.text.clone(),
+            new_width: updated.width,
+            new_height: updated.height,
+        });
+        persist_box(&state, &updated);
+        broadcast_box(&state, &updated);
+        reindex(&state);
     }
 }
 
 #[tauri::command]
 fn delete_box(state: State<AppState>, id: String) {
     let mut canvas_state = state.canvas_state.lock().unwrap();
+    let deleted_box = canvas_state.boxes.iter().find(|b| b.id == id).cloned();
+    let deleted_connections: Vec<Connection> = canvas_state.connections.iter()
+        .filter(|c| c.from == id || c.to == id)
+        .cloned()
+        .collect();
     canvas_state.boxes.retain(|b| b.id != id);
     canvas_state.connections.retain(|c| c.from != id && c.to != id);
+    drop(canvas_state);
+    if let Some(box_) = deleted_box {
+        state.history.lock().unwrap().push(Op::BoxDeleted { box_, connections: deleted_connections });
+    }
+    persist_box_deletion(&state, &id);
+    broadcast_box_deletion(&state, &id);
+    reindex(&state);
 }
 
 #[tauri::command]
@@ -190,12 +341,19 @@ fn add_connection(state: State<AppState>, from: String, to: String) {
     let mut canvas_state = state.canvas_state.lock().unwrap();
 
     // Prevent duplicate connections
-    let connection_exists = canvas_state.connections.iter().any(|c| 
+    let connection_exists = canvas_state.connections.iter().any(|c|
         (c.from == from && c.to == to) || (c.from == to && c.to == from)
     );
 
     if !connection_exists {
-        canvas_state.connections.push(Connection { from, to, r#type: ConnectionType::Forward });
+        let before = canvas_state.connections.clone();
+        let new_connection = Connection { from, to, r#type: ConnectionType::Forward };
+        canvas_state.connections.push(new_connection.clone());
+        let after = canvas_state.connections.clone();
+        drop(canvas_state);
+        state.history.lock().unwrap().push(Op::ConnectionsChanged { before, after });
+        persist_connection(&state, &new_connection);
+        broadcast_connection(&state, &new_connection);
     }
 }
 
@@ -227,6 +385,7 @@ fn select_boxes(state: State<AppState>, ids: Vec<String>) {
 #[tauri::command]
 fn toggle_connections(state: State<AppState>, from_ids: Vec<String>, to_id: String) -> CanvasState {
     let mut canvas_state = state.canvas_state.lock().unwrap();
+    let before = canvas_state.connections.clone();
 
     for from_id in from_ids {
         if from_id == to_id {
@@ -240,45 +399,76 @@ fn toggle_connections(state: State<AppState>, from_ids: Vec<String>, to_id: Stri
         if let Some(index) = connection_index {
             // Connection exists, toggle its state.
             let mut conn = canvas_state.connections.remove(index);
+            let old_from = conn.from.clone();
+            let old_to = conn.to.clone();
 
             if conn.r#type == ConnectionType::Bidirectional {
                 // Downgrade to a single connection in the opposite direction of the action.
                 conn.from = to_id.clone();
                 conn.to = from_id;
                 conn.r#type = ConnectionType::Forward;
-                canvas_state.connections.push(conn);
+                canvas_state.connections.push(conn.clone());
+                // The `connections` table is keyed by directed (from_id, to_id); if the
+                // swap above changed that key, the old row has to be deleted explicitly
+                // or it's left behind as a stale duplicate.
+                if (conn.from != old_from) || (conn.to != old_to) {
+                    persist_connection_deletion(&state, &old_from, &old_to);
+                    broadcast_connection_deletion(&state, &old_from, &old_to);
+                }
+                persist_connection(&state, &conn);
+                broadcast_connection(&state, &conn);
             } else if conn.r#type == ConnectionType::Forward {
                 if conn.from == from_id {
                     // Action A->B on existing A->B connection: remove.
+                    persist_connection_deletion(&state, &conn.from, &conn.to);
+                    broadcast_connection_deletion(&state, &conn.from, &conn.to);
                 } else { // conn.from == to_id
                     // Action A->B on existing B->A connection: upgrade.
                     conn.r#type = ConnectionType::Bidirectional;
-                    canvas_state.connections.push(conn);
+                    canvas_state.connections.push(conn.clone());
+                    persist_connection(&state, &conn);
+                    broadcast_connection(&state, &conn);
                 }
             } else { // conn.r#type == ConnectionType::None
                 // It was a connection with no direction, now it gets one.
                 conn.from = from_id;
                 conn.to = to_id.clone();
                 conn.r#type = ConnectionType::Forward;
-                canvas_state.connections.push(conn);
+                canvas_state.connections.push(conn.clone());
+                if (conn.from != old_from) || (conn.to != old_to) {
+                    persist_connection_deletion(&state, &old_from, &old_to);
+                    broadcast_connection_deletion(&state, &old_from, &old_to);
+                }
+                persist_connection(&state, &conn);
+                broadcast_connection(&state, &conn);
             }
         } else {
             // No connection exists, create a new one.
-            canvas_state.connections.push(Connection {
+            let new_connection = Connection {
                 from: from_id,
                 to: to_id.clone(),
                 r#type: ConnectionType::Forward,
-            });
+            };
+            canvas_state.connections.push(new_connection.clone());
+            persist_connection(&state, &new_connection);
+            broadcast_connection(&state, &new_connection);
         }
     }
 
-    canvas_state.clone()
+    let after = canvas_state.connections.clone();
+    let result = canvas_state.clone();
+    drop(canvas_state);
+    if before != after {
+        state.history.lock().unwrap().push(Op::ConnectionsChanged { before, after });
+    }
+    result
 }
 
 #[tauri::command]
 fn cycle_connection_type(state: State<AppState>, from: String, to: String) -> Option<Connection> {
     let mut canvas_state = state.canvas_state.lock().unwrap();
-    if let Some(connection) = canvas_state.connections.iter_mut().find(|c| 
+    let before = canvas_state.connections.clone();
+    if let Some(connection) = canvas_state.connections.iter_mut().find(|c|
         (c.from == from && c.to == to) || (c.from == to && c.to == from)
     ) {
         connection.r#type = match connection.r#type {
@@ -286,7 +476,13 @@ fn cycle_connection_type(state: State<AppState>, from: String, to: String) -> Op
             ConnectionType::Forward => ConnectionType::Bidirectional,
             ConnectionType::Bidirectional => ConnectionType::None,
         };
-        Some(connection.clone())
+        let updated = connection.clone();
+        let after = canvas_state.connections.clone();
+        drop(canvas_state);
+        state.history.lock().unwrap().push(Op::ConnectionsChanged { before, after });
+        persist_connection(&state, &updated);
+        broadcast_connection(&state, &updated);
+        Some(updated)
     } else {
         None
     }
@@ -360,6 +556,15 @@ fn move_box(state: State<AppState>, box_id: String, new_x: i32, new_y: i32) -> C
     }
 
     canvas_state.boxes = final_boxes;
+    let moves: Vec<BoxDelta> = to_update.values().map(|updated_box| {
+        let old = original_state.boxes.iter().find(|b| b.id == updated_box.id).unwrap();
+        BoxDelta { id: updated_box.id.clone(), old_x: old.x, old_y: old.y, new_x: updated_box.x, new_y: updated_box.y }
+    }).collect();
+    state.history.lock().unwrap().push(Op::BoxMoved { moves });
+    for updated_box in to_update.values() {
+        persist_box(&state, updated_box);
+        broadcast_box(&state, updated_box);
+    }
     canvas_state.clone()
 }
 
@@ -435,6 +640,15 @@ fn move_selected_boxes(state: State<AppState>, delta_x: i32, delta_y: i32) -> Ca
     }
 
     canvas_state.boxes = final_boxes;
+    let moves: Vec<BoxDelta> = to_update.values().map(|updated_box| {
+        let old = original_state.boxes.iter().find(|b| b.id == updated_box.id).unwrap();
+        BoxDelta { id: updated_box.id.clone(), old_x: old.x, old_y: old.y, new_x: updated_box.x, new_y: updated_box.y }
+    }).collect();
+    state.history.lock().unwrap().push(Op::BoxMoved { moves });
+    for updated_box in to_update.values() {
+        persist_box(&state, updated_box);
+        broadcast_box(&state, updated_box);
+    }
     canvas_state.clone()
 }
 
@@ -442,7 +656,120 @@ fn move_selected_boxes(state: State<AppState>, delta_x: i32, delta_y: i32) -> Ca
 fn load_new_state(new_state: CanvasState, state: State<AppState>) -> CanvasState {
     let mut canvas_state = state.canvas_state.lock().unwrap();
     *canvas_state = new_state;
-    canvas_state.clone()
+    if let Some(store) = state.canvas_store.lock().unwrap().as_ref() {
+        if let Err(e) = store.replace_all(&canvas_state) {
+            error!("failed to persist loaded canvas state: {}", e);
+        }
+    }
+    let result = canvas_state.clone();
+    drop(canvas_state);
+    // A wholesale replacement isn't an incremental edit of the prior
+    // document, so there's nothing coherent left for undo to step back into.
+    state.history.lock().unwrap().clear();
+    reindex(&state);
+    result
+}
+
+#[tauri::command]
+fn open_canvas(state: State<AppState>, path: String) -> Result<CanvasState, String> {
+    let store = CanvasStore::open(&path)?;
+    let loaded = store.load()?;
+    *state.canvas_store.lock().unwrap() = Some(store);
+    *state.canvas_state.lock().unwrap() = loaded.clone();
+    state.history.lock().unwrap().clear();
+    reindex(&state);
+    Ok(loaded)
+}
+
+#[tauri::command]
+fn search_boxes(state: State<AppState>, query: String, k: usize) -> Vec<(String, f32)> {
+    state.search_index.lock().unwrap().search(&query, k)
+}
+
+#[tauri::command]
+fn highlight_box(state: State<AppState>, id: String, syntax: Option<String>) -> Result<Vec<HighlightSpan>, String> {
+    let canvas_state = state.canvas_state.lock().unwrap();
+    let b = canvas_state
+        .boxes
+        .iter()
+        .find(|b| b.id == id)
+        .ok_or_else(|| format!("no box with id {id}"))?;
+    Ok(state.highlighter.highlight(&b.text, syntax.as_deref()))
+}
+
+#[tauri::command]
+fn undo(state: State<AppState>) -> CanvasState {
+    let mut canvas_state = state.canvas_state.lock().unwrap();
+    let before = canvas_state.clone();
+    if state.history.lock().unwrap().undo(&mut canvas_state) {
+        let snapshot = canvas_state.clone();
+        drop(canvas_state);
+        if let Some(store) = state.canvas_store.lock().unwrap().as_ref() {
+            if let Err(e) = store.replace_all(&snapshot) {
+                error!("failed to persist undo: {}", e);
+            }
+        }
+        reindex(&state);
+        broadcast_canvas_diff(&state, &before, &snapshot);
+        snapshot
+    } else {
+        canvas_state.clone()
+    }
+}
+
+#[tauri::command]
+fn redo(state: State<AppState>) -> CanvasState {
+    let mut canvas_state = state.canvas_state.lock().unwrap();
+    let before = canvas_state.clone();
+    if state.history.lock().unwrap().redo(&mut canvas_state) {
+        let snapshot = canvas_state.clone();
+        drop(canvas_state);
+        if let Some(store) = state.canvas_store.lock().unwrap().as_ref() {
+            if let Err(e) = store.replace_all(&snapshot) {
+                error!("failed to persist redo: {}", e);
+            }
+        }
+        reindex(&state);
+        broadcast_canvas_diff(&state, &before, &snapshot);
+        snapshot
+    } else {
+        canvas_state.clone()
+    }
+}
+
+#[tauri::command]
+fn history_length(state: State<AppState>) -> usize {
+    state.history.lock().unwrap().len()
+}
+
+#[tauri::command]
+fn save_canvas(state: State<AppState>, path: String) -> Result<(), String> {
+    let canvas_state = state.canvas_state.lock().unwrap().clone();
+    let store = CanvasStore::open(&path)?;
+    store.replace_all(&canvas_state)?;
+    *state.canvas_store.lock().unwrap() = Some(store);
+    Ok(())
+}
+
+#[tauri::command]
+fn host_session(app_handle: tauri::AppHandle, state: State<AppState>, port: u16) -> Result<(), String> {
+    let canvas = state.canvas_state.clone();
+    let handle = collab::host(app_handle, canvas, port)?;
+    *state.collab.lock().unwrap() = Some(handle);
+    Ok(())
+}
+
+#[tauri::command]
+fn join_session(app_handle: tauri::AppHandle, state: State<AppState>, url: String, last_seen_seq: Option<u64>) -> Result<(), String> {
+    let canvas = state.canvas_state.clone();
+    let handle = collab::join(app_handle, canvas, url, last_seen_seq)?;
+    *state.collab.lock().unwrap() = Some(handle);
+    Ok(())
+}
+
+#[tauri::command]
+fn leave_session(state: State<AppState>) {
+    *state.collab.lock().unwrap() = None;
 }
 
 #[tauri::command]
@@ -503,6 +830,7 @@ fn delete_file(path: String) -> Result<(), String> {
 #[derive(Default)]
 pub struct AppBuilder {
     canvas_state: CanvasState,
+    history_cap: Option<usize>,
 }
 
 impl AppBuilder {
@@ -515,9 +843,20 @@ impl AppBuilder {
         self
     }
 
+    pub fn with_history_cap(mut self, cap: usize) -> Self {
+        self.history_cap = Some(cap);
+        self
+    }
+
     pub fn build(self) -> Builder<Wry> {
     let app_state = AppState {
-            canvas_state: Mutex::new(self.canvas_state),
+            canvas_state: Arc::new(Mutex::new(self.canvas_state)),
+            workspace_watcher: Mutex::new(None),
+            canvas_store: Mutex::new(None),
+            search_index: Mutex::new(SearchIndex::new()),
+            history: Mutex::new(self.history_cap.map(History::new).unwrap_or_default()),
+            collab: Mutex::new(None),
+            highlighter: HighlightService::new(),
     };
     
         Builder::default()
@@ -548,7 +887,19 @@ impl AppBuilder {
             delete_file,
             get_workspace_path,
             set_workspace_path,
-            list_directory_contents
+            list_directory_contents,
+            start_watching_workspace,
+            stop_watching_workspace,
+            open_canvas,
+            save_canvas,
+            search_boxes,
+            highlight_box,
+            undo,
+            redo,
+            history_length,
+            host_session,
+            join_session,
+            leave_session
         ])
     }
 }
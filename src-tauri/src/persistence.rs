@@ -0,0 +1,288 @@
+// SQLite-backed canvas persistence. `Mutex<CanvasState>` in `AppState` stays
+// the hot in-memory cache; a `CanvasStore` opened alongside it is written
+// through on every box and connection edit, so a `.kairo` file holds one row
+// per box and connection instead of round-tripping as one JSON blob. Rows are
+// overwritten in place; earlier values are not kept. Selection changes
+// (`toggle_box_selection`, `clear_selection`, `select_boxes`) are not written
+// through, so a row's `selected` column is refreshed only when that box is
+// next written.
+use rusqlite::{params, Connection};
+
+use crate::{Box as CanvasBox, CanvasState, Connection as CanvasConnection, ConnectionType};
+
+const SCHEMA_VERSION: i32 = 1;
+
+pub struct CanvasStore {
+    conn: Connection,
+}
+
+fn connection_type_to_str(t: ConnectionType) -> &'static str {
+    match t {
+        ConnectionType::None => "none",
+        ConnectionType::Forward => "forward",
+        ConnectionType::Bidirectional => "bidirectional",
+    }
+}
+
+fn connection_type_from_str(s: &str) -> ConnectionType {
+    match s {
+        "forward" => ConnectionType::Forward,
+        "bidirectional" => ConnectionType::Bidirectional,
+        _ => ConnectionType::None,
+    }
+}
+
+impl CanvasStore {
+    /// Opens (creating if necessary) the `.kairo` SQLite file at `path` and
+    /// runs any pending schema migrations.
+    pub fn open(path: &str) -> Result<Self, String> {
+        let conn = Connection::open(path).map_err(|e| e.to_string())?;
+        let store = Self { conn };
+        store.migrate()?;
+        Ok(store)
+    }
+
+    fn schema_version(&self) -> Result<i32, String> {
+        self.conn
+            .execute_batch("CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL)")
+            .map_err(|e| e.to_string())?;
+        Ok(self
+            .conn
+            .query_row("SELECT version FROM schema_version LIMIT 1", [], |row| row.get(0))
+            .unwrap_or(0))
+    }
+
+    fn migrate(&self) -> Result<(), String> {
+        let mut version = self.schema_version()?;
+
+        if version < 1 {
+            self.conn
+                .execute_batch(
+                    "CREATE TABLE IF NOT EXISTS boxes (
+                        id TEXT PRIMARY KEY,
+                        x INTEGER NOT NULL,
+                        y INTEGER NOT NULL,
+                        width INTEGER NOT NULL,
+                        height INTEGER NOT NULL,
+                        text TEXT NOT NULL,
+                        selected INTEGER NOT NULL,
+                        color TEXT
+                    );
+                    CREATE TABLE IF NOT EXISTS connections (
+                        from_id TEXT NOT NULL,
+                        to_id TEXT NOT NULL,
+                        conn_type TEXT NOT NULL,
+                        PRIMARY KEY (from_id, to_id)
+                    );",
+                )
+                .map_err(|e| e.to_string())?;
+            version = 1;
+        }
+
+        self.conn
+            .execute("DELETE FROM schema_version", [])
+            .map_err(|e| e.to_string())?;
+        self.conn
+            .execute("INSERT INTO schema_version (version) VALUES (?1)", params![version])
+            .map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    /// Reads the full canvas back out of the DB, e.g. for `open_canvas`.
+    pub fn load(&self) -> Result<CanvasState, String> {
+        let mut boxes_stmt = self
+            .conn
+            .prepare("SELECT id, x, y, width, height, text, selected, color FROM boxes")
+            .map_err(|e| e.to_string())?;
+        let boxes = boxes_stmt
+            .query_map([], |row| {
+                Ok(CanvasBox {
+                    id: row.get(0)?,
+                    x: row.get(1)?,
+                    y: row.get(2)?,
+                    width: row.get(3)?,
+                    height: row.get(4)?,
+                    text: row.get(5)?,
+                    selected: row.get::<_, i64>(6)? != 0,
+                    color: row.get(7)?,
+                })
+            })
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+
+        let mut conn_stmt = self
+            .conn
+            .prepare("SELECT from_id, to_id, conn_type FROM connections")
+            .map_err(|e| e.to_string())?;
+        let connections = conn_stmt
+            .query_map([], |row| {
+                let conn_type: String = row.get(2)?;
+                Ok(CanvasConnection {
+                    from: row.get(0)?,
+                    to: row.get(1)?,
+                    r#type: connection_type_from_str(&conn_type),
+                })
+            })
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+
+        Ok(CanvasState { boxes, connections })
+    }
+
+    /// Replaces the entire contents of the store with `state`, e.g. for
+    /// `save_canvas` or the legacy `load_new_state` blob import.
+    pub fn replace_all(&self, state: &CanvasState) -> Result<(), String> {
+        self.conn.execute("DELETE FROM boxes", []).map_err(|e| e.to_string())?;
+        self.conn.execute("DELETE FROM connections", []).map_err(|e| e.to_string())?;
+        for b in &state.boxes {
+            self.upsert_box(b)?;
+        }
+        for c in &state.connections {
+            self.upsert_connection(c)?;
+        }
+        Ok(())
+    }
+
+    pub fn upsert_box(&self, b: &CanvasBox) -> Result<(), String> {
+        self.conn
+            .execute(
+                "INSERT INTO boxes (id, x, y, width, height, text, selected, color)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+                 ON CONFLICT(id) DO UPDATE SET
+                    x = excluded.x, y = excluded.y, width = excluded.width,
+                    height = excluded.height, text = excluded.text,
+                    selected = excluded.selected, color = excluded.color",
+                params![b.id, b.x, b.y, b.width, b.height, b.text, b.selected as i64, b.color],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    pub fn delete_box(&self, id: &str) -> Result<(), String> {
+        self.conn
+            .execute("DELETE FROM boxes WHERE id = ?1", params![id])
+            .map_err(|e| e.to_string())?;
+        self.conn
+            .execute(
+                "DELETE FROM connections WHERE from_id = ?1 OR to_id = ?1",
+                params![id],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    pub fn upsert_connection(&self, c: &CanvasConnection) -> Result<(), String> {
+        self.conn
+            .execute(
+                "INSERT INTO connections (from_id, to_id, conn_type)
+                 VALUES (?1, ?2, ?3)
+                 ON CONFLICT(from_id, to_id) DO UPDATE SET conn_type = excluded.conn_type",
+                params![c.from, c.to, connection_type_to_str(c.r#type)],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    pub fn delete_connection(&self, from: &str, to: &str) -> Result<(), String> {
+        self.conn
+            .execute(
+                "DELETE FROM connections WHERE (from_id = ?1 AND to_id = ?2) OR (from_id = ?2 AND to_id = ?1)",
+                params![from, to],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_box(id: &str) -> CanvasBox {
+        CanvasBox {
+            id: id.to_string(),
+            x: 1,
+            y: 2,
+            width: 100,
+            height: 50,
+            text: "hello".to_string(),
+            selected: false,
+            color: None,
+        }
+    }
+
+    fn sample_connection(from: &str, to: &str) -> CanvasConnection {
+        CanvasConnection {
+            from: from.to_string(),
+            to: to.to_string(),
+            r#type: ConnectionType::Forward,
+        }
+    }
+
+    #[test]
+    fn open_runs_migration_and_starts_empty() {
+        let store = CanvasStore::open(":memory:").unwrap();
+        assert_eq!(store.schema_version().unwrap(), SCHEMA_VERSION);
+        let state = store.load().unwrap();
+        assert!(state.boxes.is_empty());
+        assert!(state.connections.is_empty());
+    }
+
+    #[test]
+    fn upsert_box_inserts_then_updates_in_place() {
+        let store = CanvasStore::open(":memory:").unwrap();
+        let mut b = sample_box("a");
+        store.upsert_box(&b).unwrap();
+        b.text = "updated".to_string();
+        b.x = 99;
+        store.upsert_box(&b).unwrap();
+
+        let state = store.load().unwrap();
+        assert_eq!(state.boxes.len(), 1);
+        assert_eq!(state.boxes[0].text, "updated");
+        assert_eq!(state.boxes[0].x, 99);
+    }
+
+    #[test]
+    fn delete_box_also_removes_its_connections() {
+        let store = CanvasStore::open(":memory:").unwrap();
+        store.upsert_box(&sample_box("a")).unwrap();
+        store.upsert_box(&sample_box("b")).unwrap();
+        store.upsert_connection(&sample_connection("a", "b")).unwrap();
+
+        store.delete_box("a").unwrap();
+
+        let state = store.load().unwrap();
+        assert_eq!(state.boxes.len(), 1);
+        assert!(state.connections.is_empty());
+    }
+
+    #[test]
+    fn delete_connection_matches_either_direction() {
+        let store = CanvasStore::open(":memory:").unwrap();
+        store.upsert_connection(&sample_connection("a", "b")).unwrap();
+
+        store.delete_connection("b", "a").unwrap();
+
+        assert!(store.load().unwrap().connections.is_empty());
+    }
+
+    #[test]
+    fn replace_all_clears_previous_rows() {
+        let store = CanvasStore::open(":memory:").unwrap();
+        store.upsert_box(&sample_box("stale")).unwrap();
+
+        let fresh = CanvasState {
+            boxes: vec![sample_box("fresh")],
+            connections: vec![],
+        };
+        store.replace_all(&fresh).unwrap();
+
+        let state = store.load().unwrap();
+        assert_eq!(state.boxes.len(), 1);
+        assert_eq!(state.boxes[0].id, "fresh");
+    }
+}
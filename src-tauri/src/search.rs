@@ -0,0 +1,190 @@
+// TF-IDF full-text search over box text. Tokenizes every `Box.text` into
+// lowercased word terms, weights each box's sparse vector as
+// `tf(t, b) * ln(N / df(t))`, L2-normalizes it, and scores a query by dot
+// product against the (also L2-normalized) box vectors -- i.e. cosine
+// similarity.
+use std::collections::HashMap;
+
+use crate::Box as CanvasBox;
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+fn term_counts(tokens: &[String]) -> HashMap<&str, u32> {
+    let mut counts = HashMap::new();
+    for token in tokens {
+        *counts.entry(token.as_str()).or_insert(0) += 1;
+    }
+    counts
+}
+
+fn l2_normalize(vector: &mut HashMap<String, f32>) {
+    let norm = vector.values().map(|w| w * w).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        for weight in vector.values_mut() {
+            *weight /= norm;
+        }
+    }
+}
+
+/// TF-IDF index over box text, stored as one weighted term vector per box (a
+/// search scores every box). Guarded by its own lock in `AppState`, separate
+/// from the canvas lock, so a search never holds the canvas; the reindex step
+/// of an edit command does wait for a search in progress.
+#[derive(Default)]
+pub struct SearchIndex {
+    /// box id -> sparse term -> weight vector (L2-normalized).
+    box_vectors: HashMap<String, HashMap<String, f32>>,
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rebuilds the whole index from the current set of boxes. Called after
+    /// any command that mutates box text (`add_box`, `update_box_text`,
+    /// `delete_box`, `load_new_state`).
+    pub fn rebuild(&mut self, boxes: &[CanvasBox]) {
+        let n = boxes.len();
+        let mut doc_freq: HashMap<&str, u32> = HashMap::new();
+        let mut per_box_terms: Vec<(&str, HashMap<&str, u32>)> = Vec::with_capacity(n);
+
+        for b in boxes {
+            let tokens = tokenize(&b.text);
+            let counts = term_counts(&tokens);
+            for term in counts.keys() {
+                *doc_freq.entry(term).or_insert(0) += 1;
+            }
+            per_box_terms.push((b.id.as_str(), counts));
+        }
+
+        let mut box_vectors = HashMap::with_capacity(n);
+        for (id, counts) in per_box_terms {
+            let mut vector: HashMap<String, f32> = HashMap::with_capacity(counts.len());
+            for (term, tf) in counts {
+                let df = doc_freq[term] as f32;
+                let weight = tf as f32 * (n as f32 / df).ln();
+                vector.insert(term.to_string(), weight);
+            }
+            l2_normalize(&mut vector);
+            box_vectors.insert(id.to_string(), vector);
+        }
+
+        self.box_vectors = box_vectors;
+    }
+
+    /// Returns the top-`k` box ids by cosine similarity to `query`.
+    pub fn search(&self, query: &str, k: usize) -> Vec<(String, f32)> {
+        let query_tokens = tokenize(query);
+        if query_tokens.is_empty() || self.box_vectors.is_empty() {
+            return Vec::new();
+        }
+
+        let n = self.box_vectors.len() as f32;
+        let mut doc_freq: HashMap<&str, u32> = HashMap::new();
+        for vector in self.box_vectors.values() {
+            for term in vector.keys() {
+                *doc_freq.entry(term.as_str()).or_insert(0) += 1;
+            }
+        }
+
+        let query_counts = term_counts(&query_tokens);
+        let mut query_vector: HashMap<String, f32> = HashMap::with_capacity(query_counts.len());
+        for (term, tf) in query_counts {
+            let df = doc_freq.get(term).copied().unwrap_or(1) as f32;
+            let weight = tf as f32 * (n / df).ln().max(0.0);
+            query_vector.insert(term.to_string(), weight);
+        }
+        l2_normalize(&mut query_vector);
+
+        let mut scores: Vec<(String, f32)> = self
+            .box_vectors
+            .iter()
+            .map(|(id, vector)| {
+                let score = query_vector
+                    .iter()
+                    .map(|(term, weight)| weight * vector.get(term).copied().unwrap_or(0.0))
+                    .sum::<f32>();
+                (id.clone(), score)
+            })
+            .filter(|(_, score)| *score > 0.0)
+            .collect();
+
+        scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        scores.truncate(k);
+        scores
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_box(id: &str, text: &str) -> CanvasBox {
+        CanvasBox {
+            id: id.to_string(),
+            x: 0,
+            y: 0,
+            width: 0,
+            height: 0,
+            text: text.to_string(),
+            selected: false,
+            color: None,
+        }
+    }
+
+    #[test]
+    fn search_ranks_box_with_more_matching_terms_first() {
+        let mut index = SearchIndex::new();
+        // "quick" must not appear in every box, or idf(quick) = ln(N/df) is
+        // zero and both vectors score 0 regardless of term frequency.
+        index.rebuild(&[
+            sample_box("a", "the quick brown fox"),
+            sample_box("b", "quick quick quick"),
+            sample_box("c", "lazy dog sleeps"),
+        ]);
+
+        let results = index.search("quick", 10);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].0, "b");
+        assert!(results[0].1 > results[1].1);
+    }
+
+    #[test]
+    fn search_excludes_boxes_with_zero_score() {
+        let mut index = SearchIndex::new();
+        index.rebuild(&[sample_box("a", "apples"), sample_box("b", "oranges")]);
+
+        let results = index.search("apples", 10);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, "a");
+    }
+
+    #[test]
+    fn search_respects_k() {
+        let mut index = SearchIndex::new();
+        // Same reasoning as above: a distractor box keeps df(rust) < N so
+        // idf(rust) -- and therefore every score -- isn't zero.
+        index.rebuild(&[
+            sample_box("a", "rust"),
+            sample_box("b", "rust"),
+            sample_box("c", "rust"),
+            sample_box("d", "java"),
+        ]);
+
+        assert_eq!(index.search("rust", 2).len(), 2);
+    }
+
+    #[test]
+    fn empty_query_returns_no_results() {
+        let mut index = SearchIndex::new();
+        index.rebuild(&[sample_box("a", "hello world")]);
+
+        assert!(index.search("", 10).is_empty());
+    }
+}
@@ -0,0 +1,103 @@
+// Parallel, ignore-aware directory traversal backing `list_directory_contents`.
+// Walks the workspace across a thread pool via `jwalk` instead of a single
+// synchronous recursion, and prunes `.gitignore`/`.kairoignore` matches
+// before descending into them so vendored and build directories never get
+// visited at all.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use jwalk::WalkDir;
+
+use crate::FileNode;
+
+fn build_ignore_matcher(root: &Path) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(root);
+    builder.add(root.join(".gitignore"));
+    builder.add(root.join(".kairoignore"));
+    builder.build().unwrap_or_else(|_| Gitignore::empty())
+}
+
+/// Walks `root` across a thread pool, honoring `.gitignore`/`.kairoignore`,
+/// down to `max_depth` (unbounded if `None`), and returns the same sorted
+/// `FileNode` tree `list_directory_contents` used to build synchronously.
+/// The `.kairo` extension filter on files is applied as a post-filter, same
+/// as before.
+pub fn walk(root: &str, max_depth: Option<usize>) -> Result<Vec<FileNode>, String> {
+    let root_path = PathBuf::from(root);
+    if !root_path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let ignore = build_ignore_matcher(&root_path);
+    let ignore_root = root_path.clone();
+
+    let mut walker = WalkDir::new(&root_path).parallelism(jwalk::Parallelism::RayonDefaultPool);
+    if let Some(depth) = max_depth {
+        walker = walker.max_depth(depth);
+    }
+    let walker = walker.process_read_dir(move |_depth, _dir_path, _read_dir_state, children| {
+        children.retain(|entry| {
+            let Ok(entry) = entry else { return true };
+            let relative = entry.path().strip_prefix(&ignore_root).unwrap_or(entry.path()).to_path_buf();
+            !ignore
+                .matched(&relative, entry.file_type().is_dir())
+                .is_ignore()
+        });
+    });
+
+    let mut children_of: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+    let mut dirs: HashMap<PathBuf, bool> = HashMap::new();
+
+    for entry in walker {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let path = entry.path();
+        if path == root_path {
+            continue;
+        }
+
+        let is_dir = entry.file_type().is_dir();
+        if !is_dir && path.extension().map(|ext| ext != "kairo").unwrap_or(true) {
+            continue; // `.kairo`-extension post-filter for files.
+        }
+
+        let parent = path.parent().unwrap_or(&root_path).to_path_buf();
+        children_of.entry(parent).or_default().push(path.clone());
+        dirs.insert(path, is_dir);
+    }
+
+    Ok(build_tree(&root_path, &children_of, &dirs))
+}
+
+fn build_tree(dir: &Path, children_of: &HashMap<PathBuf, Vec<PathBuf>>, dirs: &HashMap<PathBuf, bool>) -> Vec<FileNode> {
+    let mut nodes: Vec<FileNode> = children_of
+        .get(dir)
+        .into_iter()
+        .flatten()
+        .map(|path| {
+            let is_directory = dirs.get(path).copied().unwrap_or(false);
+            FileNode {
+                name: path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(),
+                path: path.to_string_lossy().to_string(),
+                is_directory,
+                children: if is_directory {
+                    Some(build_tree(path, children_of, dirs))
+                } else {
+                    None
+                },
+            }
+        })
+        .collect();
+
+    nodes.sort_by(|a, b| {
+        if a.is_directory == b.is_directory {
+            a.name.cmp(&b.name)
+        } else if a.is_directory {
+            std::cmp::Ordering::Less
+        } else {
+            std::cmp::Ordering::Greater
+        }
+    });
+
+    nodes
+}
@@ -0,0 +1,65 @@
+// Live workspace-tree updates: watches the workspace directory on disk and
+// re-walks the whole workspace whenever files change, emitting the full tree
+// to the frontend instead of making it poll `list_directory_contents`. The
+// walk is not narrowed to the changed paths.
+use std::path::PathBuf;
+use std::time::Duration;
+
+use log::error;
+use notify::RecommendedWatcher;
+use notify_debouncer_mini::{new_debouncer, Debouncer};
+use tauri::{AppHandle, Emitter};
+
+use crate::list_directory_contents;
+
+const DEBOUNCE_WINDOW: Duration = Duration::from_millis(200);
+const WORKSPACE_TREE_CHANGED_EVENT: &str = "workspace-tree-changed";
+
+/// Handle to a live `notify` watcher on a workspace directory. Dropping it
+/// stops the underlying watch thread.
+pub struct WorkspaceWatcher {
+    _debouncer: Debouncer<RecommendedWatcher>,
+}
+
+/// Spawns a recursive, debounced watcher on `workspace_path` and emits
+/// `workspace-tree-changed` with the freshly-walked tree whenever a burst
+/// of filesystem events settles.
+pub fn start(app_handle: AppHandle, workspace_path: String) -> Result<WorkspaceWatcher, String> {
+    let root = PathBuf::from(&workspace_path);
+    let (tx, rx) = std::sync::mpsc::channel();
+
+    let mut debouncer = new_debouncer(DEBOUNCE_WINDOW, tx).map_err(|e| e.to_string())?;
+    debouncer
+        .watcher()
+        .watch(&root, notify::RecursiveMode::Recursive)
+        .map_err(|e| e.to_string())?;
+
+    std::thread::spawn(move || {
+        for result in rx {
+            let events = match result {
+                Ok(events) => events,
+                Err(errors) => {
+                    error!("workspace watcher error: {errors:?}");
+                    continue;
+                }
+            };
+
+            if events.is_empty() {
+                continue;
+            }
+
+            match list_directory_contents(workspace_path.clone(), None) {
+                Ok(tree) => {
+                    if let Err(e) = app_handle.emit(WORKSPACE_TREE_CHANGED_EVENT, tree) {
+                        error!("failed to emit {WORKSPACE_TREE_CHANGED_EVENT}: {e}");
+                    }
+                }
+                Err(e) => error!("failed to re-walk workspace {workspace_path}: {e}"),
+            }
+        }
+    });
+
+    Ok(WorkspaceWatcher {
+        _debouncer: debouncer,
+    })
+}